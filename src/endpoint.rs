@@ -0,0 +1,62 @@
+use std::thread;
+use std::time::Duration;
+
+use chrono::Datelike;
+use reqwest::blocking::Client;
+
+use crate::LocationRecord;
+
+/// Backfills `lines` to a running OwnTracks recorder, batching either by calendar month
+/// (the default) or by `chunk_size` records, sleeping `rate_limit_ms` between batches.
+/// Resume state is the count of records already posted, not a `tst` cutoff: `lines` is
+/// already globally sorted by `tst`, but several records can share the same whole-second
+/// `tst`, so a timestamp cutoff would skip the rest of an interrupted second. Tracking a
+/// plain offset into the sorted sequence survives that case.
+pub fn publish(lines: Vec<LocationRecord>, endpoint: &str, chunk_size: Option<usize>, rate_limit_ms: u64, state_file: &str) {
+    let resume_count = read_state(state_file).unwrap_or(0);
+    let client = Client::new();
+    let mut batch: Vec<LocationRecord> = Vec::new();
+    let mut active_month: Option<(i32, u32)> = None;
+    let mut posted_count = resume_count;
+
+    for lr in lines.into_iter().skip(resume_count) {
+        let month = {
+            let timestamp = lr.get_timestamp();
+            (timestamp.year(), timestamp.month())
+        };
+        let month_boundary = chunk_size.is_none() && active_month.is_some_and(|m| m != month);
+        let chunk_boundary = chunk_size.is_some_and(|n| batch.len() >= n);
+        if !batch.is_empty() && (month_boundary || chunk_boundary) {
+            post_batch(&client, endpoint, &batch, state_file, &mut posted_count);
+            batch.clear();
+            thread::sleep(Duration::from_millis(rate_limit_ms));
+        }
+        active_month = Some(month);
+        batch.push(lr);
+    }
+    if !batch.is_empty() {
+        post_batch(&client, endpoint, &batch, state_file, &mut posted_count);
+    }
+}
+
+fn post_batch(client: &Client, endpoint: &str, batch: &[LocationRecord], state_file: &str, posted_count: &mut usize) {
+    for lr in batch {
+        client
+            .post(endpoint)
+            .header("Content-Type", "application/json")
+            .body(lr.to_json())
+            .send()
+            .and_then(|response| response.error_for_status())
+            .unwrap_or_else(|e| panic!("failed to POST record at tst {}: {e}", lr.tst));
+        *posted_count += 1;
+        write_state(state_file, *posted_count);
+    }
+}
+
+fn read_state(state_file: &str) -> Option<usize> {
+    std::fs::read_to_string(state_file).ok().and_then(|s| s.trim().parse().ok())
+}
+
+fn write_state(state_file: &str, posted_count: usize) {
+    std::fs::write(state_file, posted_count.to_string()).expect("failed to write resume state file");
+}
@@ -4,46 +4,157 @@ use chrono::prelude::*;
 use polars::prelude::*;
 use serde::{Deserialize, Serialize};
 use serde_json;
-use clap::Parser;
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use toml;
 
-#[derive(Debug, Serialize, Deserialize)]
+mod tsdb;
+use tsdb::TsDb;
+mod endpoint;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct LocationRecord {
     #[serde(rename = "_type")]
-    record_type: String,
-    tid: String,
-    tst: i64, //TimeStamp seconds
-    #[serde(skip_serializing)]
-    timestamp_nanos: i64,
-    lat: f64,
-    lon: f64,
+    pub(crate) record_type: String,
+    pub(crate) tid: String,
+    pub(crate) tst: i64, //TimeStamp seconds
+    // Not part of the OwnTracks payload; skipped on serialize and defaulted on deserialize
+    // since the tsdb log stores it separately on `Entry` and restores it after loading.
+    #[serde(skip_serializing, default)]
+    pub(crate) timestamp_nanos: i64,
+    pub(crate) lat: f64,
+    pub(crate) lon: f64,
     #[serde(skip_serializing_if = "Option::is_none")]
-    acc: Option<i64>,
+    pub(crate) acc: Option<i64>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    alt: Option<i64>,
+    pub(crate) alt: Option<i64>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    vac: Option<i64>,
+    pub(crate) vac: Option<i64>,
+    #[serde(skip_serializing, default)]
+    pub(crate) device_tag: Option<i64>,
 }
 
 impl LocationRecord {
-    fn get_timestamp(&self) -> DateTime<Utc> {
+    pub(crate) fn get_timestamp(&self) -> DateTime<Utc> {
         DateTime::from_timestamp_nanos(self.timestamp_nanos)
     }
+    pub(crate) fn to_json(&self) -> String {
+        serde_json::to_string(&self).unwrap()
+    }
     fn create_owntrack_line(&self) -> String {
         let timestamp = self.get_timestamp().format("%Y-%m-%dT%H:%M:%SZ");
-        let record_json = serde_json::to_string(&self).unwrap();
+        let record_json = self.to_json();
         return format!("{timestamp}\t*                 \t{record_json}\n");
     }
+    // Tab-separated, COPY-friendly: None/empty becomes a literal \N so Postgres reads it as SQL NULL.
+    fn create_copy_line(&self) -> String {
+        let timestamp = self.get_timestamp().format("%Y-%m-%d %H:%M:%S");
+        let opt_i64 = |v: Option<i64>| v.map_or(String::from("\\N"), |v| v.to_string());
+        let tid = if self.tid.is_empty() { "\\N" } else { self.tid.as_str() };
+        format!(
+            "{timestamp}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\n",
+            self.lat,
+            self.lon,
+            opt_i64(self.acc),
+            opt_i64(self.alt),
+            opt_i64(self.vac),
+            tid,
+            opt_i64(self.device_tag),
+        )
+    }
 }
 
+const COPY_TABLE_DDL: &str = "CREATE TABLE locations (\n\
+    tst timestamp NOT NULL,\n\
+    lat double precision NOT NULL,\n\
+    lon double precision NOT NULL,\n\
+    acc bigint,\n\
+    alt bigint,\n\
+    vac bigint,\n\
+    tid text NOT NULL,\n\
+    device_tag bigint\n\
+);\n";
+
 #[derive(Parser)]
 #[command(version, about, long_about=None)]
 struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Transform a Google location history export and write it out via the configured backend
+    Import(ImportArgs),
+    /// Query the tsdb backend for records in a time range
+    Query(QueryArgs),
+}
+
+#[derive(Clone, ValueEnum)]
+enum Backend {
+    Rec,
+    Tsdb,
+    Copy,
+    Endpoint,
+}
+
+#[derive(Args)]
+struct ImportArgs {
     #[arg(short = 'f')]
     input_file: String,
     #[arg(short = 'i')]
-    tracker_id: String, // Arbritry two character code for OT
-    #[arg(short = 'e')]
-    exclude_device: i32 // Probably should be an optional list, i only had the one
+    tracker_id: Option<String>, // Arbritry two character code for OT; required via -i or --config
+    #[arg(short = 'e', long = "exclude-device")]
+    exclude_device: Vec<i32>, // Repeatable; devices to drop from the import
+    #[arg(long, value_parser = DateTime::parse_from_rfc3339)]
+    start: Option<DateTime<FixedOffset>>, // Only keep records at or after this instant
+    #[arg(long, value_parser = DateTime::parse_from_rfc3339)]
+    end: Option<DateTime<FixedOffset>>, // Only keep records at or before this instant
+    #[arg(long)]
+    config: Option<String>, // TOML file of defaults; CLI flags above take precedence when both are set
+    #[arg(long, value_enum, default_value_t = Backend::Rec)]
+    backend: Backend,
+    #[arg(long, default_value = "location_history.tsdb")]
+    tsdb_path: String, // Only used when --backend tsdb
+    #[arg(long)]
+    endpoint: Option<String>, // OwnTracks recorder URL; required when --backend endpoint
+    #[arg(long, default_value_t = 0)]
+    rate_limit_ms: u64, // Delay between batches when --backend endpoint
+    #[arg(long)]
+    chunk_size: Option<usize>, // Batch size when --backend endpoint; defaults to batching by month
+    #[arg(long, default_value = "endpoint_state.txt")]
+    state_file: String, // Tracks how many records have been successfully posted so a backfill can resume
+}
+
+#[derive(Args)]
+struct QueryArgs {
+    #[arg(long, default_value = "location_history.tsdb")]
+    tsdb_path: String,
+    #[arg(long, value_parser = DateTime::parse_from_rfc3339)]
+    start: Option<DateTime<FixedOffset>>,
+    #[arg(long, value_parser = DateTime::parse_from_rfc3339)]
+    end: Option<DateTime<FixedOffset>>,
+    #[arg(long)]
+    tid: Option<String>, // Filter to a single tracker id
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct Config {
+    tracker_id: Option<String>,
+    exclude_devices: Option<Vec<i32>>,
+    output_dir: Option<String>,
+    start: Option<String>, // RFC3339, parsed the same way as the CLI flag
+    end: Option<String>,
+}
+
+fn read_config(path: &str) -> Config {
+    let contents = std::fs::read_to_string(path).expect("Failed to read config file");
+    toml::from_str(&contents).expect("Failed to parse config file")
+}
+
+fn parse_rfc3339_utc(value: &str) -> DateTime<Utc> {
+    DateTime::parse_from_rfc3339(value)
+        .expect("Invalid RFC3339 timestamp in config file")
+        .with_timezone(&Utc)
 }
 
 fn read(record_location:&str) -> Result<DataFrame, PolarsError>{
@@ -54,7 +165,7 @@ fn read(record_location:&str) -> Result<DataFrame, PolarsError>{
     Ok(df)
 }
 
-fn transform(df:DataFrame, tracker_id:&str, exclude_device:i32) -> Result<Vec<LocationRecord>, PolarsError>{
+fn transform(df:DataFrame, tracker_id:&str, exclude_devices:&[i32], start:Option<DateTime<Utc>>, end:Option<DateTime<Utc>>) -> Result<Vec<LocationRecord>, PolarsError>{
     let output = df
         .clone()
         .lazy()
@@ -82,14 +193,24 @@ fn transform(df:DataFrame, tracker_id:&str, exclude_device:i32) -> Result<Vec<Lo
             col("verticalAccuracy").alias("vac"),
             col("timestamp"),
             col("tst"),
-            col("deviceTag"),
+            col("deviceTag").cast(DataType::Int64),
         ])
         .filter(
             col("deviceTag")
-                .neq(lit(exclude_device))
+                .is_in(lit(Series::new(PlSmallStr::from("exclude_devices"), exclude_devices)), false)
+                .not()
                 .or(col("deviceTag").is_null()),
         )
-        .filter(col("lat").is_not_null())
+        .filter(col("lat").is_not_null());
+    let output = match start {
+        Some(start_ts) => output.filter(col("tst").gt_eq(lit(start_ts.timestamp()))),
+        None => output,
+    };
+    let output = match end {
+        Some(end_ts) => output.filter(col("tst").lt_eq(lit(end_ts.timestamp()))),
+        None => output,
+    };
+    let output = output
         .sort(["tst"], Default::default())
         .collect()?;
     let lines: Vec<LocationRecord> = output
@@ -106,6 +227,7 @@ fn transform(df:DataFrame, tracker_id:&str, exclude_device:i32) -> Result<Vec<Lo
                 vac: row_vals[4].clone().try_into().unwrap(),
                 timestamp_nanos: row_vals[5].try_extract().unwrap(),
                 tst: row_vals[6].try_extract().unwrap(),
+                device_tag: row_vals[7].clone().try_into().unwrap(),
                 tid: String::from(tracker_id),
                 record_type: String::from("location")
             }
@@ -114,18 +236,51 @@ fn transform(df:DataFrame, tracker_id:&str, exclude_device:i32) -> Result<Vec<Lo
     return Ok(lines);
 }
 
-fn main() {
-    let args = Cli::parse();
+fn run_import(args: ImportArgs) {
+    let config = args.config.as_deref().map(read_config).unwrap_or_default();
+
     let record_location = args.input_file;
-    let tracker_id = args.tracker_id;
-    //TODO this should be an optional list to be more genericly useful
-    let exclude_device = args.exclude_device;
+    let tracker_id = args.tracker_id.or(config.tracker_id).expect("tracker_id must be set via -i or --config");
+    let exclude_devices = if !args.exclude_device.is_empty() {
+        args.exclude_device
+    } else {
+        config.exclude_devices.unwrap_or_default()
+    };
+    let output_dir = config.output_dir.unwrap_or_else(|| String::from("rust_output"));
+    let start = args.start.map(|d| d.with_timezone(&Utc))
+        .or_else(|| config.start.as_deref().map(parse_rfc3339_utc));
+    let end = args.end.map(|d| d.with_timezone(&Utc))
+        .or_else(|| config.end.as_deref().map(parse_rfc3339_utc));
 
     //Read
     let df = read(&record_location).expect("Failed to read in provided file");
     //Transform
-    let lines = transform(df, &tracker_id, exclude_device).expect("Error working with sheet data");
+    let lines = transform(df, &tracker_id, &exclude_devices, start, end).expect("Error working with sheet data");
     //Write
+    match args.backend {
+        Backend::Rec => write_rec_files(lines, &output_dir),
+        Backend::Tsdb => write_tsdb(lines, &args.tsdb_path),
+        Backend::Copy => write_copy_files(lines, &output_dir),
+        Backend::Endpoint => {
+            let endpoint_url = args.endpoint.expect("--endpoint is required for the endpoint backend");
+            endpoint::publish(lines, &endpoint_url, args.chunk_size, args.rate_limit_ms, &args.state_file)
+        }
+    }
+}
+
+fn write_copy_files(lines: Vec<LocationRecord>, output_dir: &str) {
+    std::fs::create_dir_all(output_dir).expect("unable to create output dir");
+    let tsv_path = format!("{output_dir}/locations.tsv");
+    let mut tsv_file = File::create(&tsv_path).expect("unable to open tsv file");
+    for lr in &lines {
+        tsv_file.write(lr.create_copy_line().as_bytes()).expect("failed to write tsv line");
+    }
+    let ddl_path = format!("{output_dir}/locations.sql");
+    let mut ddl_file = File::create(&ddl_path).expect("unable to open ddl file");
+    ddl_file.write(COPY_TABLE_DDL.as_bytes()).expect("failed to write ddl file");
+}
+
+fn write_rec_files(lines: Vec<LocationRecord>, output_dir: &str) {
     //Not sure if this is the most efficient way to write out
     let mut active_file = String::new();
     let mut active_lines: Vec<String> = Vec::new();
@@ -134,7 +289,7 @@ fn main() {
         let timestamp = lr.get_timestamp();
         let year = timestamp.year().to_string();
         let month = timestamp.month().to_string();
-        let line_file = format!("rust_output/{year}-{month}.rec");
+        let line_file = format!("{output_dir}/{year}-{month}.rec");
         match line_file == active_file {
             true => {}
             _ => {
@@ -153,6 +308,35 @@ fn main() {
     print!("{active_file}");
 }
 
+fn write_tsdb(lines: Vec<LocationRecord>, tsdb_path: &str) {
+    let mut db = TsDb::open(tsdb_path).expect("unable to open tsdb");
+    let count = lines.len();
+    for lr in lines {
+        db.append(lr).expect("failed to append record to tsdb");
+    }
+    println!("appended {count} records to {tsdb_path}");
+}
+
+fn run_query(args: QueryArgs) {
+    let db = TsDb::open(&args.tsdb_path).expect("unable to open tsdb");
+    let start = args.start.map(|d| d.with_timezone(&Utc));
+    let end = args.end.map(|d| d.with_timezone(&Utc));
+    for entry in db.range(start, end) {
+        if args.tid.as_deref().is_some_and(|tid| tid != entry.record.tid) {
+            continue;
+        }
+        print!("{}", entry.record.create_owntrack_line());
+    }
+}
+
+fn main() {
+    let args = Cli::parse();
+    match args.command {
+        Command::Import(import_args) => run_import(import_args),
+        Command::Query(query_args) => run_query(query_args),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::{env, path::PathBuf};
@@ -171,7 +355,8 @@ mod tests {
             lon: 64.0,
             acc: Some(20),
             alt: None,
-            vac: None
+            vac: None,
+            device_tag: None
         };
         let expected = format!("2015-01-11T12:12:00Z\t*                 \t{{\"_type\":\"location\",\"tid\":\"tt\",\"tst\":{test_tst},\"lat\":42.0,\"lon\":64.0,\"acc\":20}}\n");
         assert_eq!(test_record.create_owntrack_line(), expected)
@@ -181,7 +366,7 @@ mod tests {
         let synthetic_data: PathBuf = [env!("CARGO_MANIFEST_DIR"), "src", "test_data", "synthetic_data.json"].iter().collect();
         println!("{:?}", synthetic_data);
         let df = read(synthetic_data.to_str().unwrap()).unwrap();
-        let data = transform(df, "tt", 1).unwrap();
+        let data = transform(df, "tt", &[1], None, None).unwrap();
         assert_eq!(data.len(), 9)
     }
 }